@@ -6,13 +6,15 @@
 //! * http://www.randomhacks.net/bare-metal-rust/
 
 #![warn(missing_docs)]
-#![feature(lang_items, const_fn, unique)]
+#![feature(lang_items, const_fn, unique, alloc, alloc_error_handler)]
 #![no_std]
 
 extern crate rlibc;
 extern crate spin;
 extern crate multiboot2;
 extern crate x86;
+extern crate alloc;
+extern crate cpuio as io;
 
 #[macro_use]
 extern crate bitflags;
@@ -20,11 +22,15 @@ extern crate bitflags;
 #[macro_use]
 pub mod vga_buffer;
 
+#[macro_use]
+pub mod serial;
+
 pub mod memory;
 
 /// The main entry point for the kernel.
 #[no_mangle]
 pub extern fn rust_main(multiboot_information_address: usize) {
+	serial::init();
 	vga_buffer::clear_screen();
 	println!("Hello World{}", "!");
 
@@ -62,6 +68,16 @@ pub extern fn rust_main(multiboot_information_address: usize) {
 
 	memory::test_paging(&mut frame_allocator);
 
+	let (mut active_table, old_p4_frame) = memory::remap_the_kernel(&mut frame_allocator, &boot_info);
+	println!("remapped the kernel");
+
+	let guard_page_addr = memory::turn_old_p4_into_guard_page(
+		&mut active_table, old_p4_frame, &mut frame_allocator);
+	println!("guard page at {:#x}", guard_page_addr);
+
+	memory::heap::init_heap(&mut active_table, &mut frame_allocator);
+	println!("heap initialized");
+
 	for i in 0.. {
 		use memory::FrameAllocator;
 		if let None = frame_allocator.allocate_frame() {
@@ -73,6 +89,11 @@ pub extern fn rust_main(multiboot_information_address: usize) {
 	loop {}
 }
 
+#[alloc_error_handler]
+fn alloc_error_handler(layout: core::alloc::Layout) -> ! {
+	panic!("allocation error: {:?}", layout);
+}
+
 #[lang = "eh_personality"] extern fn eh_personality() {}
 
 #[lang = "panic_fmt"] extern fn panic_fmt(fmt: core::fmt::Arguments, file: &str, line: u32) -> ! {
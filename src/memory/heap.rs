@@ -0,0 +1,148 @@
+//! The kernel heap allocator.
+//!
+//! Backs `Box`, `Vec`, and other `alloc`-based collections with a small
+//! fixed-size-block free-list allocator running out of a single reserved
+//! virtual range.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem;
+use core::ptr;
+
+use spin::Mutex;
+
+use memory::paging::{Page, RecusivePageTable, VirtualAddress, WRITEABLE};
+use memory::FrameAllocator;
+
+/// The first virtual address reserved for the kernel heap.
+pub const HEAP_START: VirtualAddress = 0o_000_001_000_000_0000;
+
+/// The size, in bytes, of the kernel heap.
+pub const HEAP_SIZE: usize = 100 * 1024;
+
+/// The block sizes used by the free-list allocator, each a power of two.
+///
+/// An allocation is rounded up to the smallest block size that can hold it.
+/// Anything bigger than the largest block size falls through to a raw
+/// bump allocation carved directly off the end of the heap.
+const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// A single free block in one of the fixed-size free lists.
+struct FreeBlock {
+	next: Option<&'static mut FreeBlock>,
+}
+
+/// A fixed-size-block allocator with a bump-allocated fallback.
+///
+/// Each entry in `free_lists` is the head of a singly linked list of free
+/// blocks of the matching `BLOCK_SIZES` size. When a list is empty, a new
+/// block is carved off `bump_next` instead.
+struct FixedSizeBlockAllocator {
+	free_lists: [Option<&'static mut FreeBlock>; BLOCK_SIZES.len()],
+	bump_next: usize,
+	heap_end: usize,
+}
+
+impl FixedSizeBlockAllocator {
+	/// Constructs an allocator with every free list empty.
+	const fn empty() -> FixedSizeBlockAllocator {
+		FixedSizeBlockAllocator {
+			free_lists: [None, None, None, None, None, None, None, None, None],
+			bump_next: 0,
+			heap_end: 0,
+		}
+	}
+
+	/// Prepares the allocator to carve blocks out of `[heap_start, heap_start + heap_size)`.
+	///
+	/// # Safety
+	///
+	/// The caller must guarantee that range is mapped and otherwise unused.
+	unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+		self.bump_next = heap_start;
+		self.heap_end = heap_start + heap_size;
+	}
+
+	/// Bump-allocates `size` bytes aligned to `align` directly off the heap.
+	fn bump_alloc(&mut self, size: usize, align: usize) -> *mut u8 {
+		let alloc_start = align_up(self.bump_next, align);
+		let alloc_end = alloc_start.saturating_add(size);
+		if alloc_end > self.heap_end {
+			return ptr::null_mut();
+		}
+		self.bump_next = alloc_end;
+		alloc_start as *mut u8
+	}
+
+	/// Returns the index into `BLOCK_SIZES` of the smallest block that can
+	/// hold `layout`, or `None` if no block size is large enough.
+	fn list_index(layout: &Layout) -> Option<usize> {
+		let required = layout.size().max(layout.align());
+		BLOCK_SIZES.iter().position(|&size| size >= required)
+	}
+}
+
+/// Rounds `addr` up to the nearest multiple of `align`, which must be a power of two.
+fn align_up(addr: usize, align: usize) -> usize {
+	(addr + align - 1) & !(align - 1)
+}
+
+unsafe impl GlobalAlloc for Mutex<FixedSizeBlockAllocator> {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let mut allocator = self.lock();
+		match FixedSizeBlockAllocator::list_index(&layout) {
+			Some(index) => match allocator.free_lists[index].take() {
+				Some(block) => {
+					allocator.free_lists[index] = block.next.take();
+					block as *mut FreeBlock as *mut u8
+				}
+				None => {
+					let block_size = BLOCK_SIZES[index];
+					allocator.bump_alloc(block_size, block_size)
+				}
+			},
+			None => allocator.bump_alloc(layout.size(), layout.align()),
+		}
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		let mut allocator = self.lock();
+		match FixedSizeBlockAllocator::list_index(&layout) {
+			Some(index) => {
+				assert!(mem::size_of::<FreeBlock>() <= BLOCK_SIZES[index]);
+				assert!(mem::align_of::<FreeBlock>() <= BLOCK_SIZES[index]);
+
+				let new_block = FreeBlock { next: allocator.free_lists[index].take() };
+				let new_block_ptr = ptr as *mut FreeBlock;
+				new_block_ptr.write(new_block);
+				allocator.free_lists[index] = Some(&mut *new_block_ptr);
+			}
+			// Blocks carved from the bump region larger than every fixed
+			// block size are never reclaimed.
+			None => {}
+		}
+	}
+}
+
+/// The kernel's global heap allocator.
+#[global_allocator]
+static ALLOCATOR: Mutex<FixedSizeBlockAllocator> = Mutex::new(FixedSizeBlockAllocator::empty());
+
+/// Reserves and maps the kernel heap's virtual range, then hands it to the
+/// global allocator.
+///
+/// # Parameters
+///
+/// * `page_table` - The active page table to map the heap's pages through.
+/// * `allocator` - The frame allocator supplying the heap's backing frames.
+pub fn init_heap<A>(page_table: &mut RecusivePageTable, allocator: &mut A)
+	where A: FrameAllocator
+{
+	let heap_start_page = Page::containing_address(HEAP_START);
+	let heap_end_page = Page::containing_address(HEAP_START + HEAP_SIZE - 1);
+
+	page_table.map_range(heap_start_page, heap_end_page, WRITEABLE, allocator);
+
+	unsafe {
+		ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+	}
+}
@@ -1,9 +1,15 @@
-use self::paging::PhysicalAddress;
+use multiboot2::BootInformation;
+
+use self::paging::{EntryFlags, InactivePageTable, Page, PhysicalAddress, PRESENT,
+					RecusivePageTable, TemporaryPage, VirtualAddress, WRITEABLE,
+					ELF_SECTION_ALLOCATED};
+
 pub use self::area_frame_allocator::AreaFrameAllocator;
 pub use self::paging::test_paging;
 
 pub mod paging;
 pub mod area_frame_allocator;
+pub mod heap;
 
 /// The size, in bytes, of a virtual memory page.
 ///
@@ -11,7 +17,7 @@ pub mod area_frame_allocator;
 pub const PAGE_SIZE: usize = 4096;
 
 /// Represents a physical memory frame.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Frame {
 	number: usize,
 }
@@ -26,6 +32,31 @@ impl Frame {
 	fn start_address(&self) -> PhysicalAddress {
 		self.number * PAGE_SIZE
 	}
+
+	/// Retrieves an inclusive iterator over every `Frame` between `start` and `end`.
+	fn range_inclusive(start: Frame, end: Frame) -> FrameIter {
+		FrameIter { start: start, end: end }
+	}
+}
+
+/// An iterator over a contiguous, inclusive range of `Frame`s.
+struct FrameIter {
+	start: Frame,
+	end: Frame,
+}
+
+impl Iterator for FrameIter {
+	type Item = Frame;
+
+	fn next(&mut self) -> Option<Frame> {
+		if self.start <= self.end {
+			let frame = self.start;
+			self.start.number += 1;
+			Some(frame)
+		} else {
+			None
+		}
+	}
 }
 
 /// The trait defining the interface for an object which allocates `Frame`s.
@@ -45,3 +76,119 @@ pub trait FrameAllocator {
 	/// * `frame` - The frame to deallocate.
     fn deallocate_frame(&mut self, frame: Frame);
 }
+
+/// Builds a fresh kernel address space that honors each ELF section's own
+/// permissions, switches to it, and returns the newly active page table
+/// along with the frame that used to hold the old P4 table.
+///
+/// # Parameters
+///
+/// * `allocator` - The frame allocator used to build the new table and its
+///   intermediate tables.
+/// * `boot_info` - The multiboot information structure describing the
+///   kernel's ELF sections and its own location in memory.
+pub fn remap_the_kernel<A>(allocator: &mut A, boot_info: &BootInformation) -> (RecusivePageTable, Frame)
+	where A: FrameAllocator
+{
+	let mut temporary_page = TemporaryPage::new(Page::containing_address(0xcafebabe000), allocator);
+
+	let mut active_table = unsafe { RecusivePageTable::new() };
+	let mut new_table = {
+		let frame = allocator.allocate_frame().expect("no more frames");
+		InactivePageTable::new(frame, &mut active_table, &mut temporary_page)
+	};
+
+	enable_nxe_bit();
+	enable_write_protect_bit();
+
+	active_table.with(&mut new_table, &mut temporary_page, |mapper| {
+		let elf_sections_tag = boot_info.elf_sections_tag()
+			.expect("elf-sections tag required");
+
+		for section in elf_sections_tag.sections() {
+			if section.flags & ELF_SECTION_ALLOCATED == 0 {
+				// section is not loaded to memory
+				continue;
+			}
+
+			assert!(section.addr as usize % PAGE_SIZE == 0,
+				"sections must be page aligned");
+
+			println!("mapping section at addr: {:#x}, size: {:#x}",
+				section.addr, section.size);
+
+			let flags = EntryFlags::from_elf_section_flags(section.flags);
+
+			let start_frame = Frame::containing_address(section.addr as usize);
+			let end_frame = Frame::containing_address((section.addr + section.size - 1) as usize);
+			for frame in Frame::range_inclusive(start_frame, end_frame) {
+				mapper.identity_map(frame, flags, allocator);
+			}
+		}
+
+		// Identity map the VGA text buffer.
+		let vga_buffer_frame = Frame::containing_address(0xb8000);
+		mapper.identity_map(vga_buffer_frame, WRITEABLE, allocator);
+
+		// Identity map the multiboot info structure.
+		let multiboot_start = Frame::containing_address(boot_info as *const _ as usize);
+		let multiboot_end = Frame::containing_address(
+			boot_info as *const _ as usize + boot_info.total_size as usize - 1);
+		for frame in Frame::range_inclusive(multiboot_start, multiboot_end) {
+			mapper.identity_map(frame, PRESENT, allocator);
+		}
+	});
+
+	let old_table = active_table.switch(new_table);
+
+	(active_table, old_table.p4_frame())
+}
+
+/// Sets the no-execute-enable bit in the EFER MSR so the `NO_EXECUTE` entry
+/// flag is honored by the CPU.
+fn enable_nxe_bit() {
+	use x86::msr::{IA32_EFER, rdmsr, wrmsr};
+
+	let nxe_bit = 1 << 11;
+	unsafe {
+		let efer = rdmsr(IA32_EFER);
+		wrmsr(IA32_EFER, efer | nxe_bit);
+	}
+}
+
+/// Sets the write-protect bit in CR0 so the kernel cannot write to
+/// read-only pages, even while running in ring 0.
+fn enable_write_protect_bit() {
+	use x86::controlregs::{cr0, cr0_write, CR0_WRITE_PROTECT};
+
+	unsafe { cr0_write(cr0() | CR0_WRITE_PROTECT) };
+}
+
+/// Unmaps the page that used to hold the old P4 table, turning it into a
+/// guard page just below the kernel stack.
+///
+/// Once unmapped, overflowing the kernel stack hits this unmapped page and
+/// raises a page fault instead of silently corrupting adjacent memory.
+///
+/// # Parameters
+///
+/// * `active_table` - The currently active page table to unmap the guard
+///   page from.
+/// * `old_p4_frame` - The frame that used to back the old P4 table, as
+///   returned by `remap_the_kernel`.
+/// * `allocator` - The frame allocator to deallocate the unmapped frame
+///   through.
+///
+/// # Returns
+///
+/// The virtual address of the resulting guard page, so fault handlers can
+/// recognize a stack overflow when they see it faulted on.
+pub fn turn_old_p4_into_guard_page<A>(active_table: &mut RecusivePageTable,
+									   old_p4_frame: Frame,
+									   allocator: &mut A) -> VirtualAddress
+	where A: FrameAllocator
+{
+	let guard_page = Page::containing_address(old_p4_frame.start_address());
+	active_table.unmap(guard_page, allocator);
+	guard_page.start_address()
+}
@@ -67,3 +67,28 @@ bitflags! {
 		const NO_EXECUTE 		= 1 << 63,
 	}
 }
+
+/// Indicates that an ELF section should be writable.
+pub const ELF_SECTION_WRITABLE: u32 = 0x1;
+/// Indicates that an ELF section is loaded into memory.
+pub const ELF_SECTION_ALLOCATED: u32 = 0x2;
+/// Indicates that an ELF section contains executable instructions.
+pub const ELF_SECTION_EXECUTABLE: u32 = 0x4;
+
+impl EntryFlags {
+	/// Derives the paging flags implied by an ELF section header's raw
+	/// `sh_flags` bitmask: writable sections get `WRITEABLE`, and sections
+	/// without the executable bit get `NO_EXECUTE`.
+	pub fn from_elf_section_flags(flags: u32) -> EntryFlags {
+		let mut entry_flags = EntryFlags::empty();
+
+		if flags & ELF_SECTION_WRITABLE != 0 {
+			entry_flags = entry_flags | WRITEABLE;
+		}
+		if flags & ELF_SECTION_EXECUTABLE == 0 {
+			entry_flags = entry_flags | NO_EXECUTE;
+		}
+
+		entry_flags
+	}
+}
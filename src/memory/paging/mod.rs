@@ -6,6 +6,7 @@
 //! 2. The recursively mapped P4 table is owned by a RecusivePageTable struct.
 
 pub use self::entry::*;
+pub use self::temporary_page::TemporaryPage;
 
 use memory::PAGE_SIZE;
 use memory::Frame;
@@ -17,6 +18,7 @@ use core::ptr::Unique;
 
 mod entry;
 mod table;
+mod temporary_page;
 
 const ENTRY_COUNT: usize = 512;
 
@@ -25,22 +27,30 @@ pub type VirtualAddress = usize;
 
 /// Represents a page of virtual memory.
 ///
-/// A page is a fixed size chunk of memory. 
+/// A page is a fixed size chunk of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Page {
 	number: usize,
 }
 
 impl Page {
-	fn containing_address(address: VirtualAddress) -> Page {
+	/// Retrieves the `Page` which contains the given `address`.
+	pub fn containing_address(address: VirtualAddress) -> Page {
 		assert!(address < 0x0000_8000_0000_0000 || address >= 0xffff_8000_0000_0000,
 			"invalid address: 0x{:x}", address);
-		Page { number: address / PAGE_SIZE }		
+		Page { number: address / PAGE_SIZE }
 	}
 
-	fn start_address(&self) -> usize {
+	/// Retrieves the first virtual address in the `Page`.
+	pub fn start_address(&self) -> usize {
 		self.number * PAGE_SIZE
 	}
 
+	/// Retrieves an inclusive iterator over every `Page` between `start` and `end`.
+	pub fn range_inclusive(start: Page, end: Page) -> PageIter {
+		PageIter { start: start, end: end }
+	}
+
 	fn p4_index(&self) -> usize {
 		(self.number >> 27) & 0o777
 	}
@@ -58,6 +68,27 @@ impl Page {
 	}
 }
 
+/// An iterator over a contiguous, inclusive range of `Page`s.
+#[derive(Clone)]
+pub struct PageIter {
+	start: Page,
+	end: Page,
+}
+
+impl Iterator for PageIter {
+	type Item = Page;
+
+	fn next(&mut self) -> Option<Page> {
+		if self.start <= self.end {
+			let page = self.start;
+			self.start.number += 1;
+			Some(page)
+		} else {
+			None
+		}
+	}
+}
+
 /// Represents a handle for the recursive page table hierarchy.
 pub struct RecusivePageTable {
 	p4: Unique<Table<Level4>>,
@@ -173,21 +204,62 @@ impl RecusivePageTable {
 		self.map_to(page, frame, flags, allocator)
 	}
 
-	/// Unmaps a page from the associated frame.
+	/// Maps every page between `start_page` and `end_page` (inclusive),
+	/// drawing each backing frame from `allocator`.
 	///
-	/// # Parameters
+	/// Pages that are already mapped are left untouched, so the range can
+	/// be applied idempotently.
+	pub fn map_range<A>(&mut self,
+						 start_page: Page,
+						 end_page: Page,
+						 flags: EntryFlags,
+						 allocator: &mut A)
+		where A : FrameAllocator
+	{
+		self.map_range_with(start_page, end_page, flags, || {
+			allocator.allocate_frame().expect("out of memory")
+		});
+	}
+
+	/// Maps every page between `start_page` and `end_page` (inclusive),
+	/// drawing each backing frame from `f` instead of a full `FrameAllocator`.
 	///
-	/// * page - The page to unmap.
-	/// * allocator - The allocator to deallocate the mapping from.
+	/// This lets callers drive mapping from a bump allocator or any other
+	/// custom source of frames. `f` also supplies the frame of every
+	/// intermediate table that has to be created along the way, so the
+	/// frames it returns are not paired one-to-one with the pages in the
+	/// range (a closure counting up from a start frame does not give an
+	/// identity mapping). Pages that are already mapped are left untouched,
+	/// so the range can be applied idempotently; intermediate tables that
+	/// already exist are reused.
+	pub fn map_range_with<F>(&mut self,
+							  start_page: Page,
+							  end_page: Page,
+							  flags: EntryFlags,
+							  mut f: F)
+		where F: FnMut() -> Frame
+	{
+		let mut allocator = ClosureFrameAllocator(&mut f);
+
+		for page in Page::range_inclusive(start_page, end_page) {
+			if self.translate_page(page).is_none() {
+				self.map(page, flags, &mut allocator);
+			}
+		}
+	}
+
+	/// Clears the P1 entry mapping `page` and flushes it from the TLB,
+	/// without deallocating its frame or reclaiming any now-empty
+	/// intermediate table.
+	///
+	/// Used by `TemporaryPage::unmap`, whose mapped frame is always
+	/// externally owned and must outlive the temporary mapping.
+	///
+	/// # Panics
 	///
-	/// # Panics 
-	/// 
 	/// * If the page is not currently mapped.
 	/// * If the page is a huge page.
-	fn unmap<A>(&mut self, page: Page, allocator: &mut A) 
-		where A : FrameAllocator
-	{
-		// Assert that the page is mapped 
+	fn unmap_leaf(&mut self, page: Page) {
 		assert!(self.translate(page.start_address()).is_some());
 
 		let p1 = self.p4_mut()
@@ -196,13 +268,200 @@ impl RecusivePageTable {
 					 .and_then(|p2| p2.next_table_mut(page.p2_index()))
 					 .expect("mapping code does not support huge pages");
 
-		let frame = p1[page.p1_index()].pointed_frame().unwrap();
 		p1[page.p1_index()].set_unused();
 		unsafe {
 			::x86::tlb::flush(page.start_address());
 		}
-		// TODO free p(1,2,3) table if empty
-		//allocator.deallocate_frame(frame);
+	}
+
+	/// Unmaps a page from the associated frame.
+	///
+	/// Also reclaims the frame the page was backed by, and walks back up
+	/// the hierarchy freeing any P1/P2/P3 table that is left empty, so
+	/// intermediate tables don't leak as mappings churn. The P4 table and
+	/// its recursive self-mapping are never freed.
+	///
+	/// # Parameters
+	///
+	/// * page - The page to unmap.
+	/// * allocator - The allocator to deallocate reclaimed frames through.
+	///
+	/// # Panics
+	///
+	/// * If the page is not currently mapped.
+	/// * If the page is a huge page.
+	/// * If `page`'s P4 index is the recursive self-mapping entry (511).
+	pub fn unmap<A>(&mut self, page: Page, allocator: &mut A)
+		where A : FrameAllocator
+	{
+		// Assert that the page is mapped
+		assert!(self.translate(page.start_address()).is_some());
+		assert!(page.p4_index() != 511,
+			"must not unmap the P4's own recursive self-mapping entry");
+
+		let p1_is_empty = {
+			let p1 = self.p4_mut()
+						 .next_table_mut(page.p4_index())
+						 .and_then(|p3| p3.next_table_mut(page.p3_index()))
+						 .and_then(|p2| p2.next_table_mut(page.p2_index()))
+						 .expect("mapping code does not support huge pages");
+
+			let frame = p1[page.p1_index()].pointed_frame().unwrap();
+			p1[page.p1_index()].set_unused();
+			unsafe {
+				::x86::tlb::flush(page.start_address());
+			}
+			allocator.deallocate_frame(frame);
+
+			p1.is_empty()
+		};
+
+		if !p1_is_empty {
+			return;
+		}
+
+		let p2_is_empty = {
+			let p2 = self.p4_mut()
+						 .next_table_mut(page.p4_index())
+						 .and_then(|p3| p3.next_table_mut(page.p3_index()))
+						 .expect("p3 table vanished while freeing an empty p1 table");
+
+			let p1_frame = p2[page.p2_index()].pointed_frame()
+				.expect("p1 table vanished while freeing it");
+			p2[page.p2_index()].set_unused();
+			unsafe {
+				::x86::tlb::flush_all();
+			}
+			allocator.deallocate_frame(p1_frame);
+
+			p2.is_empty()
+		};
+
+		if !p2_is_empty {
+			return;
+		}
+
+		let p3_is_empty = {
+			let p3 = self.p4_mut()
+						 .next_table_mut(page.p4_index())
+						 .expect("p3 table vanished while freeing an empty p2 table");
+
+			let p2_frame = p3[page.p3_index()].pointed_frame()
+				.expect("p2 table vanished while freeing it");
+			p3[page.p3_index()].set_unused();
+			unsafe {
+				::x86::tlb::flush_all();
+			}
+			allocator.deallocate_frame(p2_frame);
+
+			p3.is_empty()
+		};
+
+		if p3_is_empty {
+			let p4 = self.p4_mut();
+			let p3_frame = p4[page.p4_index()].pointed_frame()
+				.expect("p3 table vanished while freeing it");
+			p4[page.p4_index()].set_unused();
+			unsafe {
+				::x86::tlb::flush_all();
+			}
+			allocator.deallocate_frame(p3_frame);
+		}
+	}
+
+	/// Temporarily activates `table` so `f` can edit its hierarchy through
+	/// the usual recursive addresses, then restores the original mapping.
+	///
+	/// # Parameters
+	///
+	/// * table - The inactive page table to edit.
+	/// * temporary_page - A scratch page used to reach `table`'s own P4 frame.
+	/// * f - The closure to run with `table` active; it sees `self` as if
+	///   `table` were the currently active page table.
+	pub fn with<F>(&mut self, table: &mut InactivePageTable, temporary_page: &mut TemporaryPage, f: F)
+		where F: FnOnce(&mut RecusivePageTable)
+	{
+		{
+			let backup = Frame::containing_address(self.p4()[511].pointed_frame().unwrap().start_address());
+
+			// Map the temporary page to the current P4 table so we can restore it later.
+			let p4_table = temporary_page.map_table_frame(backup, self);
+
+			// Overwrite the recursive mapping to point at the inactive table.
+			self.p4_mut()[511].set(table.p4_frame, PRESENT | WRITEABLE);
+			unsafe { ::x86::tlb::flush_all(); }
+
+			f(self);
+
+			// Restore the recursive mapping to the original P4 table.
+			p4_table[511].set(backup, PRESENT | WRITEABLE);
+			unsafe { ::x86::tlb::flush_all(); }
+		}
+
+		temporary_page.unmap(self);
+	}
+
+	/// Switches the active P4 table to `new_table`, returning the table
+	/// that was active before the switch.
+	pub fn switch(&mut self, new_table: InactivePageTable) -> InactivePageTable {
+		use x86::controlregs;
+
+		let old_table = InactivePageTable {
+			p4_frame: Frame::containing_address(unsafe { controlregs::cr3() } as usize),
+		};
+
+		unsafe {
+			controlregs::cr3_write(new_table.p4_frame.start_address() as u64);
+		}
+
+		old_table
+	}
+}
+
+/// An owned, currently-inactive page table hierarchy.
+///
+/// Used to build a new address space (e.g. for a process) without
+/// disturbing the one that is currently active.
+pub struct InactivePageTable {
+	p4_frame: Frame,
+}
+
+impl InactivePageTable {
+	/// Constructs a fresh, empty `InactivePageTable` backed by `frame`.
+	///
+	/// Zeroes the frame through `temporary_page`, then sets its own 511th
+	/// entry to point recursively back at itself.
+	pub fn new(frame: Frame, active_table: &mut RecusivePageTable, temporary_page: &mut TemporaryPage) -> InactivePageTable {
+		{
+			let table = temporary_page.map_table_frame(frame, active_table);
+			table.zero();
+			table[511].set(frame, PRESENT | WRITEABLE);
+		}
+		temporary_page.unmap(active_table);
+
+		InactivePageTable { p4_frame: frame }
+	}
+
+	/// Retrieves the frame backing this table's own P4.
+	pub fn p4_frame(&self) -> Frame {
+		self.p4_frame
+	}
+}
+
+/// Adapts a `FnMut() -> Frame` closure into a `FrameAllocator`, so
+/// `map_range_with` can drive `RecusivePageTable::map` without requiring
+/// callers to implement the full trait.
+struct ClosureFrameAllocator<'a, F: 'a>(&'a mut F);
+
+impl<'a, F> FrameAllocator for ClosureFrameAllocator<'a, F>
+	where F: FnMut() -> Frame
+{
+	fn allocate_frame(&mut self) -> Option<Frame> {
+		Some((self.0)())
+	}
+
+	fn deallocate_frame(&mut self, _frame: Frame) {
+		// map_range never unmaps; there is nothing to give back.
 	}
 }
 
@@ -0,0 +1,132 @@
+//! Defines the fixed-size table used at every level of the page table
+//! hierarchy, and the recursive lookup used to reach child tables through
+//! the recursively-mapped P4 entry.
+
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+
+use memory::FrameAllocator;
+
+use super::entry::*;
+use super::ENTRY_COUNT;
+
+/// The virtual address of the P4 table, reached through the recursive
+/// mapping installed in its own 511th entry.
+pub const P4: *mut Table<Level4> = 0xffffffff_fffff000 as *mut _;
+
+/// A single level of the page table hierarchy.
+pub struct Table<L: TableLevel> {
+	entries: [Entry; ENTRY_COUNT],
+	level: PhantomData<L>,
+}
+
+impl<L> Table<L> where L: TableLevel {
+	/// Marks every entry in the table as unused.
+	pub fn zero(&mut self) {
+		for entry in self.entries.iter_mut() {
+			entry.set_unused();
+		}
+	}
+
+	/// Counts how many entries in this table are currently in use.
+	pub fn used_count(&self) -> usize {
+		self.entries.iter().filter(|entry| !entry.is_unused()).count()
+	}
+
+	/// Determines whether every entry in this table is unused.
+	pub fn is_empty(&self) -> bool {
+		self.used_count() == 0
+	}
+}
+
+impl<L> Table<L> where L: HierarchicalLevel {
+	/// Retrieves the virtual address of the next table down, if the entry
+	/// at `index` is present and does not point at a huge page.
+	fn next_table_address(&self, index: usize) -> Option<usize> {
+		let entry_flags = self[index].flags();
+		if entry_flags.contains(PRESENT) && !entry_flags.contains(HUGE_PAGE) {
+			let table_address = self as *const _ as usize;
+			Some((table_address << 9) | (index << 12))
+		} else {
+			None
+		}
+	}
+
+	/// Retrieves a reference to the next table down, if present.
+	pub fn next_table(&self, index: usize) -> Option<&Table<L::NextLevel>> {
+		self.next_table_address(index)
+			.map(|address| unsafe { &*(address as *const _) })
+	}
+
+	/// Retrieves a mutable reference to the next table down, if present.
+	pub fn next_table_mut(&mut self, index: usize) -> Option<&mut Table<L::NextLevel>> {
+		self.next_table_address(index)
+			.map(|address| unsafe { &mut *(address as *mut _) })
+	}
+
+	/// Retrieves a mutable reference to the next table down, allocating and
+	/// zeroing a fresh table if one is not already present.
+	pub fn next_table_create<A>(&mut self, index: usize, allocator: &mut A) -> &mut Table<L::NextLevel>
+		where A: FrameAllocator
+	{
+		if self.next_table(index).is_none() {
+			assert!(!self[index].flags().contains(HUGE_PAGE),
+				"mapping code does not support huge pages");
+			let frame = allocator.allocate_frame().expect("no frames available");
+			self[index].set(frame, PRESENT | WRITEABLE);
+			self.next_table_mut(index).unwrap().zero();
+		}
+		self.next_table_mut(index).unwrap()
+	}
+}
+
+impl<L> Index<usize> for Table<L> where L: TableLevel {
+	type Output = Entry;
+
+	fn index(&self, index: usize) -> &Entry {
+		&self.entries[index]
+	}
+}
+
+impl<L> IndexMut<usize> for Table<L> where L: TableLevel {
+	fn index_mut(&mut self, index: usize) -> &mut Entry {
+		&mut self.entries[index]
+	}
+}
+
+/// Marker trait for a page table level.
+pub trait TableLevel {}
+
+/// Marker trait for a page table level whose entries point at further
+/// tables rather than at frames directly.
+pub trait HierarchicalLevel: TableLevel {
+	/// The table level one step closer to P1.
+	type NextLevel: TableLevel;
+}
+
+/// The top level of the page table hierarchy.
+pub enum Level4 {}
+/// The second level of the page table hierarchy.
+pub enum Level3 {}
+/// The third level of the page table hierarchy.
+pub enum Level2 {}
+/// The bottom level of the page table hierarchy, whose entries point
+/// directly at frames.
+pub enum Level1 {}
+
+impl TableLevel for Level4 {}
+impl TableLevel for Level3 {}
+impl TableLevel for Level2 {}
+impl TableLevel for Level1 {}
+
+impl HierarchicalLevel for Level4 {
+	type NextLevel = Level3;
+}
+
+impl HierarchicalLevel for Level3 {
+	type NextLevel = Level2;
+}
+
+impl HierarchicalLevel for Level2 {
+	type NextLevel = Level1;
+}
@@ -0,0 +1,90 @@
+//! A single scratch virtual page used to map an arbitrary physical frame
+//! into the active address space so its bytes can be read or written.
+
+use memory::{Frame, FrameAllocator};
+
+use super::{Page, RecusivePageTable, VirtualAddress, WRITEABLE};
+use super::table::{Level1, Table};
+
+/// A scratch page backed by a tiny three-frame allocator, used to map a
+/// single physical frame into the currently active address space.
+pub struct TemporaryPage {
+	page: Page,
+	allocator: TinyAllocator,
+}
+
+impl TemporaryPage {
+	/// Constructs a `TemporaryPage` at `page`, seeding its private
+	/// bookkeeping allocator with three frames drawn from `allocator`.
+	pub fn new<A>(page: Page, allocator: &mut A) -> TemporaryPage
+		where A: FrameAllocator
+	{
+		TemporaryPage {
+			page: page,
+			allocator: TinyAllocator::new(allocator),
+		}
+	}
+
+	/// Maps the temporary page to `frame` in `active_table`, returning the
+	/// virtual address its bytes can now be read or written through.
+	pub fn map(&mut self, frame: Frame, active_table: &mut RecusivePageTable) -> VirtualAddress {
+		assert!(active_table.translate_page(self.page).is_none(),
+			"temporary page is already mapped");
+		active_table.map_to(self.page, frame, WRITEABLE, &mut self.allocator);
+		self.page.start_address()
+	}
+
+	/// Unmaps the temporary page from `active_table`.
+	///
+	/// The frame this page was mapped to is externally owned (e.g. a fresh
+	/// `InactivePageTable`'s P4 frame, or the active table's own P4 frame
+	/// saved as a `with` backup) and must outlive this call, so this only
+	/// clears the leaf mapping — it never reclaims the frame or any
+	/// now-empty intermediate table through `self.allocator`, whose
+	/// three-slot capacity is reserved for this page's own P3/P2/P1 tables.
+	pub fn unmap(&mut self, active_table: &mut RecusivePageTable) {
+		active_table.unmap_leaf(self.page);
+	}
+
+	/// Maps the temporary page to `frame` and reinterprets it as a fresh
+	/// `Table<Level1>`, so the frame's contents can be zeroed and edited
+	/// before it is linked into a real page table hierarchy.
+	pub fn map_table_frame(&mut self, frame: Frame, active_table: &mut RecusivePageTable) -> &mut Table<Level1> {
+		unsafe { &mut *(self.map(frame, active_table) as *mut Table<Level1>) }
+	}
+}
+
+/// A tiny fixed-capacity `FrameAllocator` used only to satisfy the
+/// `map_to` calls a `TemporaryPage` makes on itself.
+struct TinyAllocator([Option<Frame>; 3]);
+
+impl TinyAllocator {
+	fn new<A>(allocator: &mut A) -> TinyAllocator
+		where A: FrameAllocator
+	{
+		let mut alloc = || allocator.allocate_frame();
+		let frames = [alloc(), alloc(), alloc()];
+		TinyAllocator(frames)
+	}
+}
+
+impl FrameAllocator for TinyAllocator {
+	fn allocate_frame(&mut self) -> Option<Frame> {
+		for frame_option in self.0.iter_mut() {
+			if frame_option.is_some() {
+				return frame_option.take();
+			}
+		}
+		None
+	}
+
+	fn deallocate_frame(&mut self, frame: Frame) {
+		for frame_option in self.0.iter_mut() {
+			if frame_option.is_none() {
+				*frame_option = Some(frame);
+				return;
+			}
+		}
+		panic!("Tiny allocator can only hold 3 frames.");
+	}
+}
@@ -0,0 +1,104 @@
+//! A 16550 UART serial console driver.
+//!
+//! QEMU can redirect COM1 to the host's stdout, so writing here makes
+//! kernel output capturable even when running headless, unlike the VGA
+//! buffer.
+
+use core::fmt;
+
+use io::Port;
+use spin::Mutex;
+
+/// The I/O base address of the first serial port.
+const COM1: u16 = 0x3F8;
+
+/// The primary serial port, wired up to COM1.
+pub static SERIAL1: Mutex<SerialPort> = Mutex::new(unsafe { SerialPort::new(COM1) });
+
+/// Runs the hardware init sequence for `SERIAL1`.
+pub fn init() {
+	SERIAL1.lock().init();
+}
+
+/// A 16550 UART serial port.
+pub struct SerialPort {
+	data: Port<u8>,
+	interrupt_enable: Port<u8>,
+	fifo_control: Port<u8>,
+	line_control: Port<u8>,
+	modem_control: Port<u8>,
+	line_status: Port<u8>,
+}
+
+impl SerialPort {
+	/// Constructs a `SerialPort` at `base`, without performing the
+	/// hardware init sequence yet.
+	///
+	/// # Safety
+	///
+	/// `base` must be the I/O base address of an accessible
+	/// 16550-compatible UART.
+	const unsafe fn new(base: u16) -> SerialPort {
+		SerialPort {
+			data: Port::new(base),
+			interrupt_enable: Port::new(base + 1),
+			fifo_control: Port::new(base + 2),
+			line_control: Port::new(base + 3),
+			modem_control: Port::new(base + 4),
+			line_status: Port::new(base + 5),
+		}
+	}
+
+	/// Runs the standard 16550 init sequence: disables interrupts, sets the
+	/// divisor for 38400 baud, configures 8N1 framing, and enables the FIFO.
+	fn init(&mut self) {
+		unsafe {
+			self.interrupt_enable.write(0x00); // Disable interrupts.
+
+			self.line_control.write(0x80); // Enable DLAB to set the baud rate divisor.
+			self.data.write(0x03); // Divisor low byte (38400 baud).
+			self.interrupt_enable.write(0x00); // Divisor high byte.
+			self.line_control.write(0x03); // 8 bits, no parity, one stop bit; disable DLAB.
+
+			self.fifo_control.write(0xC7); // Enable FIFO, clear it, 14-byte threshold.
+			self.modem_control.write(0x0B); // IRQs enabled, RTS/DTR set.
+		}
+	}
+
+	/// Writes a single byte, spinning until the transmitter is ready.
+	pub fn write_byte(&mut self, byte: u8) {
+		unsafe {
+			while self.line_status.read() & 0x20 == 0 {}
+			self.data.write(byte);
+		}
+	}
+}
+
+impl fmt::Write for SerialPort {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		for byte in s.bytes() {
+			self.write_byte(byte);
+		}
+		Ok(())
+	}
+}
+
+macro_rules! serial_println {
+    ($fmt:expr) => (serial_print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (serial_print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+/// Writes an already-evaluated `fmt::Arguments` to `SERIAL1`.
+///
+/// `serial_print!`/`serial_println!` call this exactly once per invocation
+/// so their arguments are evaluated a single time, matching the pattern
+/// `print!` uses to mirror output to both sinks.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+	use core::fmt::Write;
+	SERIAL1.lock().write_fmt(args).unwrap();
+}
@@ -3,6 +3,8 @@
 use core::ptr::Unique;
 use spin::Mutex;
 
+use serial::SERIAL1;
+
 const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
 
@@ -19,10 +21,20 @@ macro_rules! println {
 }
 
 macro_rules! print {
-    ($($arg:tt)*) => ({
-            use core::fmt::Write;
-            $crate::vga_buffer::WRITER.lock().write_fmt(format_args!($($arg)*)).unwrap();
-    });
+    ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
+}
+
+/// Writes an already-evaluated `fmt::Arguments` to both the VGA buffer and
+/// the serial console.
+///
+/// `print!`/`println!` call this exactly once per invocation so arguments
+/// with side effects (a counter, a volatile read, a function call) are
+/// evaluated a single time and the same output reaches both sinks.
+#[doc(hidden)]
+pub fn _print(args: ::core::fmt::Arguments) {
+	use core::fmt::Write;
+	WRITER.lock().write_fmt(args).unwrap();
+	SERIAL1.lock().write_fmt(args).unwrap();
 }
 
 /// Clears the background.